@@ -0,0 +1,125 @@
+//! C ABI over [`crate::TimecodeEngine`], generated into `include/libtimecode.h`
+//! by cbindgen. Hosts in other languages can embed the engine without
+//! depending on gsmtc or midir — they own their own MIDI transport and
+//! media source, and only need to call these functions.
+
+use crate::{FrameRate, SyncMode, TimecodeEngine};
+
+#[repr(u8)]
+pub enum TcFrameRate {
+    Fps24 = 0,
+    Fps25 = 1,
+    Fps2997 = 2,
+    Fps2997Df = 3,
+    Fps30 = 4,
+}
+
+impl From<TcFrameRate> for FrameRate {
+    fn from(value: TcFrameRate) -> Self {
+        match value {
+            TcFrameRate::Fps24 => FrameRate::Fps24,
+            TcFrameRate::Fps25 => FrameRate::Fps25,
+            TcFrameRate::Fps2997 => FrameRate::Fps2997,
+            TcFrameRate::Fps2997Df => FrameRate::Fps2997Df,
+            TcFrameRate::Fps30 => FrameRate::Fps30,
+        }
+    }
+}
+
+#[repr(u8)]
+pub enum TcSyncMode {
+    Mtc = 0,
+    MidiClock = 1,
+    Both = 2,
+}
+
+impl From<TcSyncMode> for SyncMode {
+    fn from(value: TcSyncMode) -> Self {
+        match value {
+            TcSyncMode::Mtc => SyncMode::Mtc,
+            TcSyncMode::MidiClock => SyncMode::MidiClock,
+            TcSyncMode::Both => SyncMode::Both,
+        }
+    }
+}
+
+/// Creates a new engine. The caller owns the returned pointer and must
+/// release it with `tc_engine_free`.
+#[no_mangle]
+pub extern "C" fn tc_engine_new(frame_rate: TcFrameRate, sync_mode: TcSyncMode) -> *mut TimecodeEngine {
+    Box::into_raw(Box::new(TimecodeEngine::new(frame_rate.into(), sync_mode.into())))
+}
+
+/// Frees an engine created with `tc_engine_new`. Passing a null pointer is a
+/// no-op.
+#[no_mangle]
+pub extern "C" fn tc_engine_free(engine: *mut TimecodeEngine) {
+    if engine.is_null() {
+        return;
+    }
+
+    unsafe {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Records a media-source position update, as reported at `updated_at_ms`.
+#[no_mangle]
+pub extern "C" fn tc_engine_set_position(
+    engine: *mut TimecodeEngine,
+    position_ms: i32,
+    updated_at_ms: u64,
+) {
+    let engine = unsafe { &mut *engine };
+    engine.set_position(position_ms, updated_at_ms);
+}
+
+/// Updates whether the media source is currently playing.
+#[no_mangle]
+pub extern "C" fn tc_engine_set_playing(engine: *mut TimecodeEngine, is_playing: u8) {
+    let engine = unsafe { &mut *engine };
+    engine.set_playing(is_playing != 0);
+}
+
+/// Updates the active song's timecode offset, tempo and pre-roll lead-in,
+/// and whether timecode/clock should be emitted at all. `now_ms` is the
+/// host's current clock reading, used to start the pre-roll countdown.
+#[no_mangle]
+pub extern "C" fn tc_engine_set_song(
+    engine: *mut TimecodeEngine,
+    offset_ms: i32,
+    bpm: f64,
+    pre_roll_ms: i32,
+    enabled: u8,
+    now_ms: u64,
+) {
+    let engine = unsafe { &mut *engine };
+    engine.set_song(offset_ms, bpm, pre_roll_ms, enabled != 0, now_ms);
+}
+
+/// Advances the engine to `now_ms` and copies the next due MIDI message into
+/// `out_buf` (which must be at least 10 bytes long, the size of the largest
+/// message the engine emits). Returns the number of bytes written, `0` if
+/// nothing is due yet, or `-1` if `out_buf` is too small — the message is put
+/// back at the front of the queue in that case, so retrying with a
+/// sufficiently large buffer will not lose it.
+///
+/// Call in a loop until it returns `0` to drain everything due for this tick.
+#[no_mangle]
+pub extern "C" fn tc_engine_tick(engine: *mut TimecodeEngine, now_ms: u64, out_buf: *mut u8, out_len: usize) -> i32 {
+    let engine = unsafe { &mut *engine };
+
+    match engine.tick(now_ms) {
+        Some(message) => {
+            if message.len() > out_len {
+                engine.requeue(message);
+                return -1;
+            }
+
+            let out = unsafe { std::slice::from_raw_parts_mut(out_buf, message.len()) };
+            out.copy_from_slice(&message);
+            message.len() as i32
+        }
+        None => 0,
+    }
+}
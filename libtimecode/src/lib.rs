@@ -0,0 +1,489 @@
+//! Pure MTC/MIDI Beat Clock engine, independent of any MIDI transport or
+//! media source. Hosts feed position/playback updates in and drain the
+//! resulting MIDI bytes out, either from Rust (see `TimecodeEngine`) or
+//! through the C ABI in [`ffi`].
+
+use std::collections::VecDeque;
+
+pub mod ffi;
+
+// Tolerance for "playback started from the top" when deciding MIDI Start
+// (0xFA) vs. Continue (0xFB): a genuine from-the-top start still carries a
+// few milliseconds of elapsed time by the point `advance` observes it.
+const MUSICAL_START_EPSILON_MS: i32 = 50;
+
+/*
+rr = 00: 24 frames/s
+rr = 01: 25 frames/s
+rr = 10: 29.97 frames/s (both drop-frame and non-drop-frame share this rate bit pattern)
+rr = 11: 30 frames/s
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameRate {
+    Fps24,
+    Fps25,
+    Fps2997,
+    Fps2997Df,
+    Fps30,
+}
+
+impl FrameRate {
+    pub fn rr_bits(self) -> u8 {
+        match self {
+            FrameRate::Fps24 => 0b00,
+            FrameRate::Fps25 => 0b01,
+            FrameRate::Fps2997 | FrameRate::Fps2997Df => 0b10,
+            FrameRate::Fps30 => 0b11,
+        }
+    }
+
+    // Nominal frame rate used to quantize milliseconds into frames. 29.97
+    // (drop or non-drop) is carried internally as 30 fps; drop-frame then
+    // renumbers the resulting frame count to stay aligned with wall-clock time.
+    pub fn nominal_fps(self) -> i32 {
+        match self {
+            FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Fps2997 | FrameRate::Fps2997Df => 30,
+            FrameRate::Fps30 => 30,
+        }
+    }
+}
+
+// `mtc` sends timecode only, `midiclock` sends Beat Clock + transport only,
+// `both` sends both in parallel on the sender loop.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SyncMode {
+    Mtc,
+    MidiClock,
+    Both,
+}
+
+impl SyncMode {
+    pub fn sends_mtc(self) -> bool {
+        matches!(self, SyncMode::Mtc | SyncMode::Both)
+    }
+
+    pub fn sends_midi_clock(self) -> bool {
+        matches!(self, SyncMode::MidiClock | SyncMode::Both)
+    }
+}
+
+// Renumber a nominal-30fps frame count using the standard SMPTE drop-frame
+// rule: drop frames 00 and 01 at the start of every minute, except minutes
+// divisible by 10, so that 29.97 timecode tracks wall-clock time.
+fn apply_drop_frame(n_nominal: i32) -> i32 {
+    let d = n_nominal / 17982;
+    let m = n_nominal % 17982;
+
+    if m > 1 {
+        n_nominal + 18 * d + 2 * ((m - 2) / 1798)
+    } else {
+        n_nominal + 18 * d
+    }
+}
+
+// Decompose a millisecond position into hours/minutes/seconds/frames at the
+// given frame rate, applying drop-frame renumbering for Fps2997Df.
+pub fn timecode_components(position: i32, frame_rate: FrameRate) -> (i32, i32, i32, i32) {
+    // Wrap to a 24h clock so a negative pre-roll countdown (counting up from
+    // `-pre_roll_ms` toward zero) still decomposes into valid BCD fields
+    // instead of casting a negative hour/minute/second to `u8`.
+    const MS_PER_DAY: i64 = 24 * 3600 * 1000;
+    let position = (position as i64).rem_euclid(MS_PER_DAY) as i32;
+
+    if frame_rate == FrameRate::Fps2997Df {
+        let n_nominal = ((position as i64 * 30 + 500) / 1000) as i32;
+        let n = apply_drop_frame(n_nominal);
+
+        let frames = n % 30;
+        let total_seconds = n / 30;
+        let seconds = total_seconds % 60;
+        let total_minutes = total_seconds / 60;
+        let minutes = total_minutes % 60;
+        let hours = total_minutes / 60;
+
+        return (hours, minutes, seconds, frames);
+    }
+
+    let hours: i32 = position / 3600000;
+    let remaining_milliseconds: i32 = position % 3600000;
+
+    let minutes: i32 = remaining_milliseconds / 60000;
+    let remaining_milliseconds: i32 = remaining_milliseconds % 60000;
+
+    let seconds: i32 = remaining_milliseconds / 1000;
+    let remaining_milliseconds: i32 = remaining_milliseconds % 1000;
+
+    let frames: i32 = remaining_milliseconds * frame_rate.nominal_fps() / 1000;
+
+    (hours, minutes, seconds, frames)
+}
+
+// Builds the MTC full-frame SysEx message for `position`. Pure byte
+// construction; sending it over a MIDI connection is the host's job.
+pub fn build_position_message(position: i32, frame_rate: FrameRate) -> [u8; 10] {
+    let (hours, minutes, seconds, frames) = timecode_components(position, frame_rate);
+
+    // Fields are already in range: `timecode_components` wraps to a 24h clock
+    let hours_bcd: u8 = hours as u8;
+    let minutes_bcd: u8 = minutes as u8;
+    let seconds_bcd: u8 = seconds as u8;
+    let frames_bcd: u8 = frames as u8;
+
+    let hours_rate_bcd: u8 = frame_rate.rr_bits() << 5 | hours_bcd;
+
+    [
+        0xF0,
+        0x7F,
+        0x7F,
+        0x01,
+        0x01,
+        hours_rate_bcd,
+        minutes_bcd,
+        seconds_bcd,
+        frames_bcd,
+        0xF7,
+    ]
+}
+
+// Builds the single MTC quarter-frame message selected by `message_index`
+// (0..=7) for `position`.
+pub fn build_quarter_frame_message(
+    position: i32,
+    message_index: u8,
+    frame_rate: FrameRate,
+) -> [u8; 2] {
+    let (hours, minutes, seconds, frames) = timecode_components(position, frame_rate);
+
+    let frames_low_nibble: u8 = (frames & 0x0F) as u8;
+    let frames_high_nibble: u8 = ((frames >> 4) & 0x01) as u8;
+
+    let seconds_low_nibble: u8 = (seconds & 0x0F) as u8;
+    let seconds_high_nibble: u8 = ((seconds >> 4) & 0x03) as u8;
+
+    let minutes_low_nibble: u8 = (minutes & 0x0F) as u8;
+    let minutes_high_nibble: u8 = ((minutes >> 4) & 0x03) as u8;
+
+    let hours_low_nibble: u8 = (hours & 0x0F) as u8;
+    let rate: u8 = frame_rate.rr_bits();
+    let hours_high_nibble: u8 = ((hours >> 4) & 0x01) as u8 | (rate << 1);
+
+    let quarter_frames = [
+        0xF1,
+        frames_low_nibble,
+        0xF1,
+        frames_high_nibble | 0x10,
+        0xF1,
+        seconds_low_nibble | 0x20,
+        0xF1,
+        seconds_high_nibble | 0x30,
+        0xF1,
+        minutes_low_nibble | 0x40,
+        0xF1,
+        minutes_high_nibble | 0x50,
+        0xF1,
+        hours_low_nibble | 0x60,
+        0xF1,
+        hours_high_nibble | 0x70,
+    ];
+
+    let mut messages = quarter_frames.chunks(2).skip(message_index as usize);
+    let msg = messages.next().unwrap();
+
+    [msg[0], msg[1]]
+}
+
+// MIDI Song Position Pointer: a 14-bit count of elapsed sixteenth notes,
+// split into 7-bit LSB/MSB data bytes.
+pub fn song_position_pointer(position_ms: i32, bpm: f64) -> [u8; 3] {
+    let sixteenth_notes = (position_ms as f64 * bpm * 4.0 / 60000.0).round() as i32;
+    let sixteenth_notes = sixteenth_notes.clamp(0, 0x3FFF);
+
+    [
+        0xF2,
+        (sixteenth_notes & 0x7F) as u8,
+        ((sixteenth_notes >> 7) & 0x7F) as u8,
+    ]
+}
+
+// A read-only snapshot of engine state, returned by `TimecodeEngine::snapshot`
+// for status/metrics reporting.
+pub struct EngineSnapshot {
+    pub position_ms: i32,
+    pub timecode: String,
+    pub is_playing: bool,
+    pub enabled_for_song: bool,
+    pub offset_ms: i32,
+    // Whether the engine is currently advancing timecode, covering both real
+    // playback and a pre-roll lead-in — the same condition `advance` gates
+    // MTC emission on.
+    pub is_running: bool,
+}
+
+// Stateful engine mirroring the sender loop in the `windows_media_timecode`
+// binary: the host feeds position/playback/song updates in, then calls
+// `tick` on its own cadence to drain the MIDI bytes due to be sent.
+pub struct TimecodeEngine {
+    frame_rate: FrameRate,
+    sync_mode: SyncMode,
+    song_offset_ms: i32,
+    bpm: f64,
+    pre_roll_ms: i32,
+    pre_roll_started_at_ms: Option<u64>,
+    enabled_for_song: bool,
+    is_playing: bool,
+    was_playing: bool,
+    base_position_ms: i32,
+    last_update_ms: u64,
+    last_sent_update_ms: u64,
+    message_index: u8,
+    next_quarter_frame_ms: u64,
+    next_clock_pulse_ms: u64,
+    pending: VecDeque<Vec<u8>>,
+}
+
+impl TimecodeEngine {
+    pub fn new(frame_rate: FrameRate, sync_mode: SyncMode) -> Self {
+        TimecodeEngine {
+            frame_rate,
+            sync_mode,
+            song_offset_ms: 0,
+            bpm: 120.0,
+            pre_roll_ms: 0,
+            pre_roll_started_at_ms: None,
+            enabled_for_song: false,
+            is_playing: false,
+            was_playing: false,
+            base_position_ms: 0,
+            last_update_ms: 0,
+            last_sent_update_ms: 0,
+            message_index: 0,
+            next_quarter_frame_ms: 0,
+            next_clock_pulse_ms: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    // Record a media-source position update, as reported at `updated_at_ms`
+    // (the host's own monotonic/wall-clock millisecond timestamp).
+    pub fn set_position(&mut self, position_ms: i32, updated_at_ms: u64) {
+        self.base_position_ms = position_ms;
+        self.last_update_ms = updated_at_ms;
+    }
+
+    pub fn set_playing(&mut self, is_playing: bool) {
+        self.is_playing = is_playing;
+    }
+
+    // Recognizes a song, arming a `pre_roll_ms` chase-lock lead-in if it
+    // wasn't already enabled: receivers then get continuously advancing
+    // timecode counting up through zero ahead of the real cue, instead of a
+    // jump straight to the live position once playback starts. `now_ms` is
+    // the host's current clock reading, used to start the lead-in countdown.
+    pub fn set_song(&mut self, offset_ms: i32, bpm: f64, pre_roll_ms: i32, enabled: bool, now_ms: u64) {
+        let newly_enabled = enabled && !self.enabled_for_song;
+
+        self.song_offset_ms = offset_ms;
+        self.bpm = bpm;
+        self.pre_roll_ms = pre_roll_ms.max(0);
+        self.enabled_for_song = enabled;
+
+        if newly_enabled {
+            self.pre_roll_started_at_ms = Some(now_ms);
+        } else if !enabled {
+            self.pre_roll_started_at_ms = None;
+        }
+    }
+
+    pub fn set_offset(&mut self, offset_ms: i32) {
+        self.song_offset_ms = offset_ms;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled_for_song = enabled;
+        if !enabled {
+            self.pre_roll_started_at_ms = None;
+        }
+    }
+
+    // Whether continuous timecode should currently be running: either real
+    // playback, or a pre-roll lead-in counting up toward the cue point.
+    fn is_running(&self) -> bool {
+        self.enabled_for_song && (self.is_playing || self.pre_roll_started_at_ms.is_some())
+    }
+
+    fn current_position(&self, now_ms: u64) -> i32 {
+        if !self.enabled_for_song {
+            return 0;
+        }
+
+        if self.is_playing {
+            let mut position = self.base_position_ms + self.song_offset_ms;
+            let elapsed = now_ms.checked_sub(self.last_update_ms).unwrap_or(0);
+            position += elapsed as i32;
+            return position;
+        }
+
+        // Pre-roll: count up from -pre_roll_ms toward zero, holding at zero
+        // if the real cue still hasn't arrived by the time we reach it.
+        // Outside a lead-in (paused after real playback), hold the paused position.
+        let started_at = match self.pre_roll_started_at_ms {
+            Some(started_at) => started_at,
+            None => return self.base_position_ms + self.song_offset_ms,
+        };
+        let elapsed = now_ms.saturating_sub(started_at) as i32;
+
+        (-self.pre_roll_ms + elapsed).min(0)
+    }
+
+    // A read-only view of the engine's current state, for status/metrics
+    // reporting. Does not advance or mutate the engine.
+    pub fn snapshot(&self, now_ms: u64) -> EngineSnapshot {
+        let position = self.current_position(now_ms);
+        let (hours, minutes, seconds, frames) = timecode_components(position, self.frame_rate);
+
+        EngineSnapshot {
+            position_ms: position,
+            timecode: format!("{:02}:{:02}:{:02}:{:02}", hours, minutes, seconds, frames),
+            is_playing: self.is_playing,
+            enabled_for_song: self.enabled_for_song,
+            offset_ms: self.song_offset_ms,
+            is_running: self.sync_mode.sends_mtc() && self.is_running(),
+        }
+    }
+
+    // Advance the engine to `now_ms` (the host's current clock reading) and
+    // return the next MIDI message due to be sent, if any. Call repeatedly
+    // until it returns `None` to drain everything due this tick.
+    pub fn tick(&mut self, now_ms: u64) -> Option<Vec<u8>> {
+        if let Some(message) = self.pending.pop_front() {
+            return Some(message);
+        }
+
+        self.advance(now_ms);
+        self.pending.pop_front()
+    }
+
+    // Puts a message just returned by `tick` back at the front of the queue.
+    // For callers (e.g. `ffi::tc_engine_tick`) that received a message but
+    // couldn't consume it this call — an undersized output buffer shouldn't
+    // silently drop it from the stream.
+    pub fn requeue(&mut self, message: Vec<u8>) {
+        self.pending.push_front(message);
+    }
+
+    fn advance(&mut self, now_ms: u64) {
+        // Real playback has reached the cue; the lead-in is over.
+        if self.is_playing {
+            self.pre_roll_started_at_ms = None;
+        }
+
+        let position = self.current_position(now_ms);
+
+        if self.sync_mode.sends_mtc() {
+            if self.last_update_ms != self.last_sent_update_ms {
+                self.pending
+                    .push_back(build_position_message(position, self.frame_rate).to_vec());
+                self.last_sent_update_ms = self.last_update_ms;
+            }
+
+            // Quarter frames are sent 4 per nominal frame (8 messages span 2
+            // frames); gating on elapsed time keeps a `tick` drain loop
+            // finite instead of re-enqueueing a message on every call.
+            if self.is_running() && now_ms >= self.next_quarter_frame_ms {
+                self.pending.push_back(
+                    build_quarter_frame_message(position, self.message_index, self.frame_rate)
+                        .to_vec(),
+                );
+                self.message_index = (self.message_index + 1) % 8;
+
+                let interval_ms = 1000.0 / (self.frame_rate.nominal_fps() as f64 * 4.0);
+                self.next_quarter_frame_ms = now_ms + interval_ms.round().max(1.0) as u64;
+            }
+        }
+
+        // Tracked as is_playing && enabled_for_song so that disabling the
+        // song while it's still playing is also a stop transition, not just
+        // the media source pausing.
+        let running_now = self.is_playing && self.enabled_for_song;
+
+        if self.sync_mode.sends_midi_clock() {
+            if running_now && !self.was_playing {
+                // Musical position is play position + song offset — the same
+                // value already carried in `position` and sent as the SPP, so
+                // the Start/Continue decision and the SPP agree on what "at
+                // the top" means. A tight tolerance (rather than `== 0`)
+                // absorbs the few milliseconds of elapsed time that have
+                // always ticked by between the position update landing and
+                // this check running, even for a genuine from-the-top start.
+                if position.abs() <= MUSICAL_START_EPSILON_MS {
+                    self.pending.push_back(vec![0xFA]);
+                } else {
+                    self.pending
+                        .push_back(song_position_pointer(position, self.bpm).to_vec());
+                    self.pending.push_back(vec![0xFB]);
+                }
+                self.next_clock_pulse_ms = now_ms;
+            } else if !running_now && self.was_playing {
+                self.pending.push_back(vec![0xFC]);
+            }
+
+            if running_now && now_ms >= self.next_clock_pulse_ms {
+                self.pending.push_back(vec![0xF8]);
+                let interval_ms = (60000.0 / (self.bpm * 24.0)).max(1.0);
+                self.next_clock_pulse_ms = now_ms + interval_ms.round() as u64;
+            }
+        }
+
+        self.was_playing = running_now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_frame_skips_two_labels_at_the_top_of_most_minutes() {
+        // 00:00:59;29, the nominal frame right before a minute rolls over.
+        assert_eq!(apply_drop_frame(1799), 1799);
+        // 00:01:00;02 — frames 00 and 01 are skipped, so frame 1800 renumbers to 1802.
+        assert_eq!(apply_drop_frame(1800), 1802);
+    }
+
+    #[test]
+    fn drop_frame_does_not_skip_at_the_tenth_minute() {
+        // 17982 nominal frames is exactly the 10-minute mark; unlike every
+        // other minute, no labels are skipped here.
+        assert_eq!(apply_drop_frame(17982), 18000);
+    }
+
+    #[test]
+    fn timecode_components_reflects_the_drop_frame_rollover() {
+        assert_eq!(
+            timecode_components(59_983, FrameRate::Fps2997Df),
+            (0, 0, 59, 29)
+        );
+        assert_eq!(
+            timecode_components(60_000, FrameRate::Fps2997Df),
+            (0, 1, 0, 2)
+        );
+    }
+
+    #[test]
+    fn timecode_components_wraps_negative_pre_roll_to_24h() {
+        assert_eq!(timecode_components(-2_000, FrameRate::Fps25), (23, 59, 58, 0));
+    }
+
+    #[test]
+    fn song_position_pointer_encodes_sixteenth_notes() {
+        // 1s at 120bpm is 8 sixteenth notes: 0xF2, LSB, MSB.
+        assert_eq!(song_position_pointer(1_000, 120.0), [0xF2, 8, 0]);
+    }
+
+    #[test]
+    fn song_position_pointer_clamps_negative_position_to_zero() {
+        assert_eq!(song_position_pointer(-5_000, 120.0), [0xF2, 0, 0]);
+    }
+}
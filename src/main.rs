@@ -1,121 +1,60 @@
-use std::sync::atomic::AtomicBool;
 use std::time::SystemTime;
 use std::{
     io::{stdin, stdout, Write},
-    sync::{
-        atomic::{AtomicI32, AtomicUsize},
-        Arc,
-    },
+    sync::{Arc, Mutex},
     time::UNIX_EPOCH,
 };
 
 use gsmtc::{ManagerEvent::*, PlaybackStatus, SessionUpdateEvent::*};
+use libtimecode::{FrameRate, SyncMode, TimecodeEngine};
 use midir::{MidiOutput, MidiOutputPort};
 
-fn send_position(
-    conn_out: &mut midir::MidiOutputConnection,
-    position: i32,
-) -> Result<(), midir::SendError> {
-    let hours: i32 = position / 3600000;
-    let remaining_milliseconds: i32 = position % 3600000;
-
-    let minutes: i32 = remaining_milliseconds / 60000;
-    let remaining_milliseconds: i32 = remaining_milliseconds % 60000;
-
-    let seconds: i32 = remaining_milliseconds / 1000;
-    let remaining_milliseconds: i32 = remaining_milliseconds % 1000;
-
-    let frames: i32 = remaining_milliseconds * 25 / 1000;
-
-    // Ensure values are within BCD range
-    let hours_bcd: u8 = hours as u8;
-    let minutes_bcd: u8 = minutes as u8;
-    let seconds_bcd: u8 = seconds as u8;
-    let frames_bcd: u8 = frames as u8;
-
-    /*
-    rr = 00: 24 frames/s
-    rr = 01: 25 frames/s
-    rr = 10: 29.97 frames/s (SMPTE drop-frame timecode)
-    rr = 11: 30 frames/s
-    */
-
-    let rr: u8 = 0b01; // 25 frames/s
-
-    let hours_rate_bcd: u8 = rr << 5 | hours_bcd;
-
-    conn_out.send(&[
-        0xF0,
-        0x7F,
-        0x7F,
-        0x01,
-        0x01,
-        hours_rate_bcd,
-        minutes_bcd,
-        seconds_bcd,
-        frames_bcd,
-        0xF7,
-    ])
+#[cfg(feature = "status")]
+mod status;
+
+#[cfg(feature = "status")]
+fn get_status_port(config: &serde_json::Value) -> Option<u16> {
+    config
+        .get("statusPort")
+        .unwrap_or(&serde_json::Value::Null)
+        .as_u64()
+        .map(|port| port as u16)
 }
 
-fn send_mtc_quarter_frame(
-    conn_out: &mut midir::MidiOutputConnection,
-    position: i32,
-    message_index: u8,
-) -> Result<(), midir::SendError> {
-    let hours: i32 = position / 3600000;
-    let remaining_milliseconds: i32 = position % 3600000;
-
-    let minutes: i32 = remaining_milliseconds / 60000;
-    let remaining_milliseconds: i32 = remaining_milliseconds % 60000;
-
-    let seconds: i32 = remaining_milliseconds / 1000;
-    let remaining_milliseconds: i32 = remaining_milliseconds % 1000;
-
-    let frames: i32 = remaining_milliseconds * 25 / 1000;
-
-    let frames_low_nibble: u8 = (frames & 0x0F) as u8;
-    let frames_high_nibble: u8 = ((frames >> 4) & 0x01) as u8;
-
-    let seconds_low_nibble: u8 = (seconds & 0x0F) as u8;
-    let seconds_high_nibble: u8 = ((seconds >> 4) & 0x03) as u8;
-
-    let minutes_low_nibble: u8 = (minutes & 0x0F) as u8;
-    let minutes_high_nibble: u8 = ((minutes >> 4) & 0x03) as u8;
-
-    let hours_low_nibble: u8 = (hours & 0x0F) as u8;
-    let rate: u8 = 0b01; // 25 frames/s
-    let hours_high_nibble: u8 = ((hours >> 4) & 0x01) as u8 | (rate << 1);
-
-    let quarter_frames = [
-        0xF1,
-        frames_low_nibble,
-        0xF1,
-        frames_high_nibble | 0x10,
-        0xF1,
-        seconds_low_nibble | 0x20,
-        0xF1,
-        seconds_high_nibble | 0x30,
-        0xF1,
-        minutes_low_nibble | 0x40,
-        0xF1,
-        minutes_high_nibble | 0x50,
-        0xF1,
-        hours_low_nibble | 0x60,
-        0xF1,
-        hours_high_nibble | 0x70,
-    ];
-
-    let messages = quarter_frames.chunks(2);
-
-    // Send only the requested quarter frame (by message_index)
-    let msg = messages
-        .skip(message_index as usize)
-        .take(1)
-        .next()
-        .unwrap();
-
-    conn_out.send(msg)
+fn get_frame_rate(config: &serde_json::Value) -> FrameRate {
+    match config.get("frameRate").unwrap_or(&serde_json::Value::Null) {
+        serde_json::Value::String(s) => match s.as_str() {
+            "24" => FrameRate::Fps24,
+            "25" => FrameRate::Fps25,
+            "29.97" => FrameRate::Fps2997,
+            "29.97df" => FrameRate::Fps2997Df,
+            "30" => FrameRate::Fps30,
+            other => {
+                eprintln!("Unknown frameRate {:?}, falling back to 25", other);
+                FrameRate::Fps25
+            }
+        },
+        serde_json::Value::Number(n) => match n.as_f64().unwrap_or(25.0) {
+            v if (v - 24.0).abs() < 0.01 => FrameRate::Fps24,
+            v if (v - 29.97).abs() < 0.01 => FrameRate::Fps2997,
+            v if (v - 30.0).abs() < 0.01 => FrameRate::Fps30,
+            _ => FrameRate::Fps25,
+        },
+        _ => FrameRate::Fps25,
+    }
+}
+
+fn get_sync_mode(config: &serde_json::Value) -> SyncMode {
+    match config
+        .get("syncMode")
+        .unwrap_or(&serde_json::Value::Null)
+        .as_str()
+        .unwrap_or("mtc")
+    {
+        "midiclock" => SyncMode::MidiClock,
+        "both" => SyncMode::Both,
+        _ => SyncMode::Mtc,
+    }
 }
 
 fn get_song(
@@ -158,15 +97,122 @@ fn get_song_offset(song: &serde_json::Value) -> Result<i32, Box<dyn std::error::
     Ok(offset as i32)
 }
 
+fn get_pre_roll_seconds(value: &serde_json::Value, default: f64) -> f64 {
+    value
+        .get("preRollSeconds")
+        .unwrap_or(&serde_json::Value::Null)
+        .as_f64()
+        .unwrap_or(default)
+}
+
+fn get_song_bpm(song: &serde_json::Value) -> f64 {
+    song.get("bpm")
+        .unwrap_or(&serde_json::Value::Null)
+        .as_f64()
+        .unwrap_or(120.0)
+}
+
+// Resolves which output ports to open, in order. A configured `midiDevices`
+// array is used verbatim; otherwise falls back to the single-port behavior
+// (auto-select if there's only one, prompt interactively otherwise).
+fn select_output_port_names(
+    midi_out: &MidiOutput,
+    out_ports: &[MidiOutputPort],
+    config: &serde_json::Value,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if out_ports.is_empty() {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "no output port found",
+        )));
+    }
+
+    let configured_devices = config
+        .get("midiDevices")
+        .unwrap_or(&serde_json::Value::Null)
+        .as_array();
+
+    if let Some(devices) = configured_devices {
+        let names: Vec<String> = devices
+            .iter()
+            .filter_map(|device| device.as_str().map(|s| s.to_string()))
+            .collect();
+
+        if !names.is_empty() {
+            return Ok(names);
+        }
+    }
+
+    if out_ports.len() == 1 {
+        let name = midi_out.port_name(&out_ports[0])?;
+        println!("Choosing the only available output port: {}", name);
+        return Ok(vec![name]);
+    }
+
+    println!("\nAvailable output ports:");
+    for (i, p) in out_ports.iter().enumerate() {
+        println!("{}: {}", i, midi_out.port_name(p).unwrap());
+    }
+    print!("Please select output port: ");
+    stdout().flush()?;
+    let mut input = String::new();
+    stdin().read_line(&mut input)?;
+
+    let selected_port_index = input
+        .trim()
+        .parse::<usize>()
+        .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
+
+    let selected_port = out_ports.get(selected_port_index).ok_or_else(|| {
+        Box::new(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "invalid output port selected",
+        )) as Box<dyn std::error::Error>
+    })?;
+
+    Ok(vec![midi_out.port_name(selected_port)?])
+}
+
+// Opens a fresh MIDI output connection to the port named `name`. A separate
+// `MidiOutput` instance is needed per connection, since `connect` consumes it.
+fn open_output_connection(
+    name: &str,
+) -> Result<midir::MidiOutputConnection, Box<dyn std::error::Error>> {
+    let midi_out = MidiOutput::new("Timecode")?;
+
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|p| midi_out.port_name(p).map(|n| n == name).unwrap_or(false))
+        .ok_or_else(|| {
+            Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("output port not found: {}", name),
+            )) as Box<dyn std::error::Error>
+        })?;
+
+    Ok(midi_out.connect(&port, "Timecode")?)
+}
+
+// Fans a MIDI message out to every open connection. A dead port logs and is
+// skipped rather than panicking the whole sender loop.
+fn send_to_all(conn_outs: &mut [midir::MidiOutputConnection], message: &[u8]) {
+    for conn_out in conn_outs.iter_mut() {
+        if let Err(e) = conn_out.send(message) {
+            eprintln!("Error sending MIDI message: {}", e);
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let song_offset = Arc::new(AtomicI32::new(0));
-    let enabled_for_song = Arc::new(AtomicBool::new(false));
-    let play_position = Arc::new(AtomicI32::new(0));
-    let last_play_position_update = Arc::new(AtomicUsize::new(0));
-    let last_sent_position_update = Arc::new(AtomicUsize::new(0));
-    let is_playing = Arc::new(AtomicBool::new(false));
-
     // read config file from argument (if provided) or use default
     let config_file = std::env::args().nth(1).unwrap_or("config.json".to_string());
 
@@ -196,7 +242,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .as_bool()
         .unwrap_or(false);
 
-    let midi_out: MidiOutput = match MidiOutput::new("Timecode") {
+    let default_pre_roll_seconds = get_pre_roll_seconds(&config, 0.0);
+
+    let frame_rate = get_frame_rate(&config);
+    let sync_mode = get_sync_mode(&config);
+
+    let engine = Arc::new(Mutex::new(TimecodeEngine::new(frame_rate, sync_mode)));
+
+    #[cfg(feature = "status")]
+    let status_state = status::StatusState::new(engine.clone());
+
+    #[cfg(feature = "status")]
+    if let Some(port) = get_status_port(&config) {
+        tokio::spawn(status::run(port, status_state.clone()));
+    }
+
+    let probe_midi_out: MidiOutput = match MidiOutput::new("Timecode") {
         Ok(midi_out) => midi_out,
         Err(e) => {
             eprintln!("Error creating MIDI output: {}", e);
@@ -207,136 +268,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    // Get an output port (read from console if multiple are available)
-    let out_ports = midi_out.ports();
-    let out_port: &MidiOutputPort = match out_ports.len() {
-        0 => {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "no output port found",
-            )) as Box<dyn std::error::Error>)
-        }
-        1 => {
-            println!(
-                "Choosing the only available output port: {}",
-                midi_out.port_name(&out_ports[0]).unwrap()
-            );
-            &out_ports[0]
-        }
-        _ => {
-            let config_midi_device = config.get("midiDevice").unwrap_or(&serde_json::Value::Null);
-
-            let config_midi_device = match config_midi_device {
-                serde_json::Value::String(s) => s,
-                _ => "",
-            };
-
-            let mut selected_port: Option<&MidiOutputPort> = None;
-
-            for (i, p) in out_ports.iter().enumerate() {
-                if midi_out.port_name(p).unwrap() == config_midi_device {
-                    println!(
-                        "Choosing the configured output port: {}",
-                        midi_out.port_name(p).unwrap()
-                    );
-                    selected_port = Some(&p);
-                    break;
-                }
-            }
-
-            if selected_port.is_none() {
-                println!("\nAvailable output ports:");
-                for (i, p) in out_ports.iter().enumerate() {
-                    println!("{}: {}", i, midi_out.port_name(p).unwrap());
-                }
-                print!("Please select output port: ");
-                stdout().flush()?;
-                let mut input = String::new();
-                stdin().read_line(&mut input)?;
+    let out_ports = probe_midi_out.ports();
+    let selected_names = select_output_port_names(&probe_midi_out, &out_ports, &config)?;
+    drop(probe_midi_out);
 
-                let selected_port_index = input
-                    .trim()
-                    .parse::<usize>()
-                    .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
-
-                selected_port = out_ports.get(selected_port_index)
-            }
+    let mut conn_outs: Vec<midir::MidiOutputConnection> = Vec::new();
 
-            selected_port.ok_or_else(|| {
-                Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "invalid output port selected",
-                )) as Box<dyn std::error::Error>
-            })?
+    for name in &selected_names {
+        match open_output_connection(name) {
+            Ok(conn) => conn_outs.push(conn),
+            Err(e) => eprintln!("Error opening MIDI output {}: {}", name, e),
         }
-    };
+    }
 
-    let mut conn_out = midi_out.connect(out_port, "Timecode")?;
+    if conn_outs.is_empty() {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "no MIDI output could be opened",
+        )));
+    }
 
     // Send timecode message of time 00:00:00:00
-    match send_position(&mut conn_out, 0) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error sending timecode message: {}", e);
-            return Err(Box::new(e) as Box<dyn std::error::Error>);
-        }
-    }
+    send_to_all(&mut conn_outs, &libtimecode::build_position_message(0, frame_rate));
 
     // async send timecode message every second
-    let cloned_play_position = play_position.clone();
-    let cloned_song_offset = song_offset.clone();
-    let cloned_last_play_position_update = last_play_position_update.clone();
-    let cloned_last_sent_position_update = last_sent_position_update.clone();
-    let cloned_is_playing = is_playing.clone();
-    let cloned_enabled_for_song = enabled_for_song.clone();
+    let cloned_engine = engine.clone();
 
     tokio::spawn(async move {
-        let mut message_index = 0;
-
         loop {
-            let mut position =
-                cloned_play_position.load(std::sync::atomic::Ordering::Relaxed) as i32;
-            let song_offset = cloned_song_offset.load(std::sync::atomic::Ordering::Relaxed);
-            let last_update =
-                cloned_last_play_position_update.load(std::sync::atomic::Ordering::Relaxed);
-            let last_sent_update =
-                cloned_last_sent_position_update.load(std::sync::atomic::Ordering::Relaxed);
-            let is_playing = cloned_is_playing.load(std::sync::atomic::Ordering::Relaxed);
-            let enabled_for_song =
-                cloned_enabled_for_song.load(std::sync::atomic::Ordering::Relaxed);
-
-            position = position + song_offset;
-
-            let now = SystemTime::now();
-            let now: std::time::Duration =
-                now.duration_since(UNIX_EPOCH).expect("Time went backwards");
-
-            let elapsed = now
-                .as_millis()
-                .checked_sub(last_update as u128)
-                .unwrap_or(0);
-
-            if is_playing {
-                position = position + elapsed as i32;
-            }
-
-            if !enabled_for_song {
-                position = 0;
-            }
-
-            if last_update != last_sent_update {
-                send_position(&mut conn_out, position).unwrap();
-                cloned_last_sent_position_update
-                    .store(last_update, std::sync::atomic::Ordering::Relaxed);
-            }
+            let now = now_ms();
 
-            if is_playing && enabled_for_song {
-                send_mtc_quarter_frame(&mut conn_out, position, message_index).unwrap();
+            loop {
+                let message = cloned_engine.lock().unwrap().tick(now);
+                match message {
+                    Some(message) => send_to_all(&mut conn_outs, &message),
+                    None => break,
+                }
             }
 
-            message_index = (message_index + 1) % 8;
-
-            tokio::time::sleep(tokio::time::Duration::from_millis(1000 / 25 / 8)).await;
+            tokio::time::sleep(tokio::time::Duration::from_millis(
+                1000 / frame_rate.nominal_fps() as u64 / 8,
+            ))
+            .await;
         }
     });
 
@@ -351,12 +324,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             } => {
                 println!("Created session: {{id={session_id}, source={source}}}");
 
-                let play_position = play_position.clone();
-                let last_play_position_update = last_play_position_update.clone();
-                let is_playing = is_playing.clone();
-                let song_offset = song_offset.clone();
-                let enabled_for_song = enabled_for_song.clone();
+                #[cfg(feature = "status")]
+                status_state
+                    .metrics
+                    .sessions_created
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+                let engine = engine.clone();
                 let config = config.clone();
+                #[cfg(feature = "status")]
+                let status_state = status_state.clone();
 
                 tokio::spawn(async move {
                     while let Some(evt) = rx.recv().await {
@@ -365,24 +342,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 let timeline = model.timeline.as_mut();
                                 timeline.map(|timeline| {
                                     let position: i32 = (timeline.position / 10000) as i32;
-                                    play_position
-                                        .store(position, std::sync::atomic::Ordering::Relaxed);
-                                    println!("Timeline position: {:#?} ", position);
-
-                                    let updated_at: usize = timeline.last_updated_at_ms as usize;
+                                    let updated_at: u64 = timeline.last_updated_at_ms as u64;
 
+                                    println!("Timeline position: {:#?} ", position);
                                     println!("Timeline updated at: {:#?}", updated_at);
 
-                                    last_play_position_update
-                                        .store(updated_at, std::sync::atomic::Ordering::Relaxed);
+                                    engine.lock().unwrap().set_position(position, updated_at);
                                 });
                                 let playback = model.playback.as_mut();
                                 playback.map(|playback| {
                                     println!("Playback status: {:#?}", playback.status);
-                                    is_playing.store(
-                                        playback.status == PlaybackStatus::Playing,
-                                        std::sync::atomic::Ordering::Relaxed,
-                                    );
+                                    engine
+                                        .lock()
+                                        .unwrap()
+                                        .set_playing(playback.status == PlaybackStatus::Playing);
                                 });
 
                                 //println!("[{session_id}/{source}] Model updated: {model:#?}")
@@ -394,6 +367,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     let song_artist = media.artist.as_str();
                                     println!("Song: {} - {}", song_title, song_artist);
 
+                                    #[cfg(feature = "status")]
+                                    {
+                                        let mut now_playing =
+                                            status_state.now_playing.lock().unwrap();
+                                        now_playing.title = song_title.to_string();
+                                        now_playing.artist = song_artist.to_string();
+                                    }
+
                                     let song = get_song(&config, song_title, song_artist)
                                         .unwrap_or(serde_json::Value::Null);
 
@@ -401,13 +382,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                         && disable_songs_outside_config)
                                     {
                                         if (disable_songs_outside_config) {
-                                            enabled_for_song
-                                                .store(false, std::sync::atomic::Ordering::Relaxed);
+                                            engine.lock().unwrap().set_enabled(false);
                                         }
                                     }
 
                                     if (song == serde_json::Value::Null) {
-                                        song_offset.store(0, std::sync::atomic::Ordering::Relaxed);
+                                        engine.lock().unwrap().set_offset(0);
                                         println!("Song not found in config");
                                         return;
                                     }
@@ -418,9 +398,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                         println!("Song offset: {}", offset);
                                     }
 
-                                    song_offset.store(offset, std::sync::atomic::Ordering::Relaxed);
-                                    enabled_for_song
-                                        .store(true, std::sync::atomic::Ordering::Relaxed);
+                                    let bpm = get_song_bpm(&song);
+                                    println!("Song bpm: {}", bpm);
+
+                                    let pre_roll_seconds =
+                                        get_pre_roll_seconds(&song, default_pre_roll_seconds);
+                                    let pre_roll_ms = (pre_roll_seconds * 1000.0) as i32;
+
+                                    engine
+                                        .lock()
+                                        .unwrap()
+                                        .set_song(offset, bpm, pre_roll_ms, true, now_ms());
                                 });
                                 //println!("[{session_id}/{source}] Media updated: {model:#?}")
                             }
@@ -429,7 +417,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!("[{session_id}/{source}] exited event-loop");
                 });
             }
-            SessionRemoved { session_id } => println!("Session {{id={session_id}}} was removed"),
+            SessionRemoved { session_id } => {
+                #[cfg(feature = "status")]
+                status_state
+                    .metrics
+                    .sessions_removed
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+                println!("Session {{id={session_id}}} was removed")
+            }
             CurrentSessionChanged {
                 session_id: Some(id),
             } => println!("Current session: {id}"),
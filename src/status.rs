@@ -0,0 +1,105 @@
+//! Optional HTTP status/metrics endpoint (feature = "status"). Exposes the
+//! live state held by the `TimecodeEngine` so operators can monitor a
+//! show-control rig remotely instead of watching the console spew.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use axum::{extract::State, routing::get, Json, Router};
+use libtimecode::TimecodeEngine;
+use serde_json::{json, Value};
+
+#[derive(Default)]
+pub struct NowPlaying {
+    pub title: String,
+    pub artist: String,
+}
+
+pub struct StatusMetrics {
+    pub sessions_created: AtomicU64,
+    pub sessions_removed: AtomicU64,
+}
+
+impl StatusMetrics {
+    pub fn new() -> Self {
+        StatusMetrics {
+            sessions_created: AtomicU64::new(0),
+            sessions_removed: AtomicU64::new(0),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct StatusState {
+    pub engine: Arc<Mutex<TimecodeEngine>>,
+    pub now_playing: Arc<Mutex<NowPlaying>>,
+    pub metrics: Arc<StatusMetrics>,
+}
+
+impl StatusState {
+    pub fn new(engine: Arc<Mutex<TimecodeEngine>>) -> Self {
+        StatusState {
+            engine,
+            now_playing: Arc::new(Mutex::new(NowPlaying::default())),
+            metrics: Arc::new(StatusMetrics::new()),
+        }
+    }
+}
+
+async fn status_json(State(state): State<StatusState>) -> Json<Value> {
+    let snapshot = state.engine.lock().unwrap().snapshot(crate::now_ms());
+    let now_playing = state.now_playing.lock().unwrap();
+
+    Json(json!({
+        "playPositionMs": snapshot.position_ms,
+        "timecode": snapshot.timecode,
+        "isPlaying": snapshot.is_playing,
+        "enabledForSong": snapshot.enabled_for_song,
+        "songTitle": now_playing.title,
+        "songArtist": now_playing.artist,
+        "timecodeOffsetMs": snapshot.offset_ms,
+    }))
+}
+
+async fn metrics_text(State(state): State<StatusState>) -> String {
+    let snapshot = state.engine.lock().unwrap().snapshot(crate::now_ms());
+    let emitting = if snapshot.is_running { 1 } else { 0 };
+
+    format!(
+        "# HELP timecode_sessions_created_total Media sessions created since startup\n\
+         # TYPE timecode_sessions_created_total counter\n\
+         timecode_sessions_created_total {}\n\
+         # HELP timecode_sessions_removed_total Media sessions removed since startup\n\
+         # TYPE timecode_sessions_removed_total counter\n\
+         timecode_sessions_removed_total {}\n\
+         # HELP timecode_position_milliseconds Current transmitted timecode position\n\
+         # TYPE timecode_position_milliseconds gauge\n\
+         timecode_position_milliseconds {}\n\
+         # HELP timecode_emitting Whether timecode is currently being emitted\n\
+         # TYPE timecode_emitting gauge\n\
+         timecode_emitting {}\n",
+        state.metrics.sessions_created.load(Ordering::Relaxed),
+        state.metrics.sessions_removed.load(Ordering::Relaxed),
+        snapshot.position_ms,
+        emitting,
+    )
+}
+
+pub async fn run(port: u16, state: StatusState) {
+    let app = Router::new()
+        .route("/status", get(status_json))
+        .route("/metrics", get(metrics_text))
+        .with_state(state);
+
+    let addr = format!("0.0.0.0:{}", port);
+
+    match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => {
+            println!("Status endpoint listening on {}", addr);
+            if let Err(e) = axum::serve(listener, app).await {
+                eprintln!("Status server error: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Error binding status endpoint on {}: {}", addr, e),
+    }
+}